@@ -17,6 +17,7 @@ pub(crate) struct HeaderInfo {
 pub type ColumnIndex = usize;
 pub type ColumnName = String;
 
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) struct HeaderColumns {
     pub columns: HashMap<ColumnIndex, ColumnName>,
 }
@@ -126,7 +127,7 @@ fn take_header_info_kv<'i>(input: &'i str) -> IResult<&'i str, (&'i str, Value)>
     }
 }
 
-fn parse_header_info<'i>(input: &'i str) -> IResult<&'i str, HeaderInfo> {
+pub(crate) fn parse_header_info<'i>(input: &'i str) -> IResult<&'i str, HeaderInfo> {
     let (remainder, v_of_kv): (&'i str, Vec<_>) =
         all_consuming(preceded(
             tuple((char('\"'), space0)),
@@ -162,8 +163,28 @@ fn parse_header_info<'i>(input: &'i str) -> IResult<&'i str, HeaderInfo> {
     Ok((remainder, h))
 }
 
-fn parser_header_column(x: &str) -> IResult<&str, HeaderColumns> {
-    unimplemented!("nope, not yet")
+/// Build a zero-based column name index from a header row's already-split
+/// fields (e.g. the fields of a csv `A,I,time` record).
+///
+/// Returns `None` if `fields` doesn't look like a header row at all (fewer
+/// than two columns, an empty name, or a name that parses as a number, which
+/// indicates a data row instead) so callers can use this as a genuine
+/// "is this the header?" discriminator rather than something that always matches.
+pub(crate) fn parser_header_column(fields: &[&str]) -> Option<HeaderColumns> {
+    if fields.len() < 2 {
+        return None;
+    }
+
+    let mut columns = HashMap::with_capacity(fields.len());
+    for (idx, field) in fields.iter().enumerate() {
+        let name = field.trim();
+        if name.is_empty() || name.parse::<f64>().is_ok() {
+            return None;
+        }
+        columns.insert(idx, name.to_owned());
+    }
+
+    Some(HeaderColumns { columns })
 }
 
 #[cfg(test)]
@@ -202,4 +223,26 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn columns() {
+        let res = parser_header_column(&["A", " I", " time"]).unwrap();
+
+        let mut expected = HashMap::new();
+        expected.insert(0, "A".to_owned());
+        expected.insert(1, "I".to_owned());
+        expected.insert(2, "time".to_owned());
+
+        assert_eq!(res.columns, expected);
+    }
+
+    #[test]
+    fn columns_rejects_data_row() {
+        assert!(parser_header_column(&["77", "0", "2"]).is_none());
+    }
+
+    #[test]
+    fn columns_rejects_single_field() {
+        assert!(parser_header_column(&["time"]).is_none());
+    }
 }
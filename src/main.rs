@@ -10,56 +10,313 @@ use serde::Deserialize;
 use log;
 use log::{debug, info, trace, warn};
 use pretty_env_logger;
-use std::convert::{Into, TryInto};
 
 mod header;
+mod regression;
 
 use header::*;
 
-#[derive(Debug, Deserialize)]
-struct Record {
-    idx: u32,
-    time_ms: u32,
-}
-
-impl Record {
-    pub fn as_tuple(&self) -> (f64, f64) {
-        (self.idx.into(), self.time_ms.into())
-    }
-}
+const TIME_COLUMN: &'static str = "time";
 
 const USAGE: &'static str = "
 plem
 
 Usage:
-  plem [--title=<title>] [--label=<label>]  <file>
+  plem [--title=<title>] [--format=<format>] [--size=<size>] [--input=<input>...] --out=<path> [<file>]
   plem --version
 
 Options:
-  --version            Show version.
-  -h --help            Show this screen.
-  --title=<title>      Header to use in the plot title.
-  --label=<label>      Label name for the legend.
+  --version             Show version.
+  -h --help             Show this screen.
+  --title=<title>       Header to use in the plot title.
+  --format=<format>     Output format, `png` or `svg` [default: png].
+  --size=<size>         Image size as `WIDTHxHEIGHT` [default: 1024x768].
+  --input=<input>       Labeled CSV input, repeatable: `--input=<label>=<path>`.
+  --out=<path>          Where to write the rendered chart.
+
+`<file>`, when given, is read as an additional CSV input, labeled with its
+file name stem. With
+neither `<file>` nor `--input`, the CSV is read from stdin.
 ";
 
 #[derive(Debug, Deserialize)]
 struct Args {
     flag_version: bool,
-    arg_file: std::path::PathBuf,
-    flag_label: String,
+    arg_file: Option<std::path::PathBuf>,
     flag_title: String,
+    flag_format: String,
+    flag_size: String,
+    flag_input: Vec<String>,
+    flag_out: std::path::PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Png,
+    Svg,
+}
+
+impl std::str::FromStr for Format {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "png" => Ok(Format::Png),
+            "svg" => Ok(Format::Svg),
+            other => Err(format_err!(
+                "Unknown --format `{}`, expected `png` or `svg`",
+                other
+            )),
+        }
+    }
+}
+
+fn parse_size(s: &str) -> Result<(u32, u32)> {
+    let mut parts = s.splitn(2, |c| c == 'x' || c == 'X');
+    let w = parts
+        .next()
+        .ok_or_else(|| format_err!("Missing width in --size=`{}`", s))?;
+    let h = parts
+        .next()
+        .ok_or_else(|| format_err!("Missing height in --size=`{}`", s))?;
+
+    let w = w
+        .trim()
+        .parse::<u32>()
+        .map_err(|e| format_err!("Invalid width `{}` in --size: {}", w, e))?;
+    let h = h
+        .trim()
+        .parse::<u32>()
+        .map_err(|e| format_err!("Invalid height `{}` in --size: {}", h, e))?;
+
+    Ok((w, h))
+}
+
+/// One `--input=<label>=<path>` entry.
+struct InputSpec {
+    label: String,
+    path: std::path::PathBuf,
+}
+
+fn parse_input_arg(raw: &str) -> Result<InputSpec> {
+    let mut parts = raw.splitn(2, '=');
+    let label = parts
+        .next()
+        .filter(|s| !s.is_empty())
+        .ok_or_else(|| format_err!("Missing label in --input=`{}`, expected <label>=<path>", raw))?;
+    let path = parts
+        .next()
+        .ok_or_else(|| format_err!("Missing path in --input=`{}`, expected <label>=<path>", raw))?;
+
+    Ok(InputSpec {
+        label: label.to_owned(),
+        path: std::path::PathBuf::from(path),
+    })
+}
+
+/// Tracks a running `(x, y)` bounding box in a single pass, so callers don't
+/// need to buffer a series to learn its plotting range.
+#[derive(Debug, Clone, Copy)]
+struct RangeAccumulator {
+    x_min: f64,
+    x_max: f64,
+    y_min: f64,
+    y_max: f64,
+}
+
+impl Default for RangeAccumulator {
+    fn default() -> Self {
+        RangeAccumulator {
+            x_min: f64::MAX,
+            x_max: f64::MIN,
+            y_min: f64::MAX,
+            y_max: f64::MIN,
+        }
+    }
+}
+
+impl RangeAccumulator {
+    fn update(&mut self, x: f64, y: f64) {
+        self.x_min = self.x_min.min(x);
+        self.x_max = self.x_max.max(x);
+        self.y_min = self.y_min.min(y);
+        self.y_max = self.y_max.max(y);
+    }
+
+    fn merge(&mut self, other: &RangeAccumulator) {
+        self.update(other.x_min, other.y_min);
+        self.update(other.x_max, other.y_max);
+    }
+
+    fn as_ranges(&self) -> (std::ops::Range<f32>, std::ops::Range<f32>) {
+        (
+            self.x_min as f32..self.x_max as f32,
+            self.y_min as f32..self.y_max as f32,
+        )
+    }
+}
+
+/// A single benchmark series, parsed from one CSV input (an `--input` file,
+/// the positional `<file>`, or stdin).
+struct Series {
+    label: String,
+    data: Vec<(f64, f64)>,
+    component_rows: Vec<Vec<f64>>,
+    times: Vec<f64>,
+    component_count: usize,
+    range: RangeAccumulator,
+}
+
+impl Series {
+    fn fit(&self) -> Result<regression::Coefficients> {
+        if self.component_count <= 1 {
+            regression::fit_simple(&self.data)
+        } else {
+            regression::fit_multi(&self.component_rows, &self.times)
+        }
+    }
+
+    /// Per-column mean of `component_rows`, used to project a multi-component
+    /// fit onto a single plotted axis.
+    fn component_means(&self) -> Vec<f64> {
+        let n = self.component_rows.len() as f64;
+        let mut sums = vec![0.0; self.component_count];
+        for row in &self.component_rows {
+            for (sum, v) in sums.iter_mut().zip(row.iter()) {
+                *sum += v;
+            }
+        }
+        sums.into_iter().map(|sum| sum / n).collect()
+    }
+}
+
+/// Parse a single benchmark CSV (header-info line, `A,I,time`-style column
+/// line, then data rows) read from `reader` into a labeled [`Series`].
+fn parse_series<R: io::Read>(label: &str, reader: R) -> Result<Series> {
+    let buffered = std::io::BufReader::with_capacity(4096, reader);
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .delimiter(b',')
+        .flexible(true)
+        .from_reader(buffered);
+
+    let mut data = Vec::with_capacity(128);
+    let mut component_rows: Vec<Vec<f64>> = Vec::with_capacity(128);
+    let mut times: Vec<f64> = Vec::with_capacity(128);
+    let mut range = RangeAccumulator::default();
+
+    // Set once the `A,I,time` style header row has been parsed; decides which
+    // CSV column is the `time` axis and which ones are components.
+    let mut columns_found = false;
+    let mut time_idx: Option<ColumnIndex> = None;
+    let mut component_idxs: Vec<ColumnIndex> = Vec::new();
+
+    for (line, rec) in rdr.records().enumerate() {
+        let line = line + 1;
+        let rec = rec.map_err(|e| format_err!("[{}] line {}: failed to parse csv: {}", label, line, e))?;
+
+        if !columns_found {
+            if let Ok((_, info)) = header::parse_header_info(rec.as_slice()) {
+                println!("[{}] Found header info {:?}", label, info);
+                continue;
+            }
+
+            let fields = rec.iter().collect::<Vec<_>>();
+            if let Some(columns) = header::parser_header_column(&fields) {
+                let found_time_idx = columns
+                    .columns
+                    .iter()
+                    .find(|(_, name)| name.as_str() == TIME_COLUMN)
+                    .map(|(idx, _)| *idx);
+
+                // A row only counts as the real header once it names a `time`
+                // column; otherwise it's likely the benchmark info line,
+                // which the csv reader splits into non-numeric fields that
+                // also happen to look column-shaped, and we should keep
+                // scanning for the actual header instead of giving up here.
+                if let Some(idx) = found_time_idx {
+                    println!("[{}] Found header columns {:?}", label, columns);
+                    columns_found = true;
+                    time_idx = Some(idx);
+                    component_idxs = columns
+                        .columns
+                        .keys()
+                        .cloned()
+                        .filter(|&i| i != idx)
+                        .collect();
+                    component_idxs.sort();
+                    continue;
+                }
+
+                warn!(
+                    "[{}] line {}: ignoring column-like row without a `{}` column: {:?}",
+                    label, line, TIME_COLUMN, columns
+                );
+                continue;
+            }
+
+            warn!("[{}] line {}: ignoring line before header was recognized: {:?}", label, line, rec);
+            continue;
+        }
+
+        let time_idx = time_idx.expect("columns_found implies time_idx is Some; qed");
+        let get = |idx: ColumnIndex| -> Result<f64> {
+            rec.get(idx)
+                .ok_or_else(|| format_err!("[{}] line {}: missing column {}", label, line, idx))?
+                .trim()
+                .parse::<f64>()
+                .map_err(|e| format_err!("[{}] line {}: failed to parse column {} (`{}`): {}", label, line, idx, rec.get(idx).unwrap_or(""), e))
+        };
+
+        let time = get(time_idx)?;
+        let components = component_idxs
+            .iter()
+            .map(|&idx| get(idx))
+            .collect::<Result<Vec<f64>>>()?;
+        let x = components.get(0).copied().unwrap_or(0.0);
+
+        range.update(x, time);
+        data.push((x, time));
+        times.push(time);
+        component_rows.push(components);
+    }
+
+    if data.len() < 2 {
+        bail!("[{}] Only one datapoint, go home", label);
+    }
+
+    Ok(Series {
+        label: label.to_owned(),
+        data,
+        component_rows,
+        times,
+        component_count: component_idxs.len(),
+        range,
+    })
 }
 
 use plotters::prelude::*;
 
-fn plot(
-    dest: &std::path::Path,
-    label: &str,
+/// Colors cycled across series, reused for both the scatter points and the
+/// fitted line of a given series.
+const PALETTE: &[RGBColor] = &[RED, BLUE, GREEN, MAGENTA, CYAN, BLACK];
+
+struct PlotSeries<'a> {
+    label: &'a str,
+    data: &'a [(f64, f64)],
+    fit: Option<(f64, f64)>,
+    color: RGBColor,
+}
+
+fn render<DB: DrawingBackend>(
+    root: DrawingArea<DB, plotters::coord::Shift>,
     title: &str,
-    data: &[(f64, f64)],
+    series: &[PlotSeries],
     ranged: (std::ops::Range<f32>, std::ops::Range<f32>),
-) -> Result<()> {
-    let root = BitMapBackend::new(dest.to_str().unwrap(), (1024, 768)).into_drawing_area();
+) -> Result<()>
+where
+    DB::ErrorType: std::error::Error + Send + Sync + 'static,
+{
     root.fill(&WHITE)?;
     let mut chart = ChartBuilder::on(&root)
         .caption(title, ("sans-serif", 50).into_font())
@@ -71,14 +328,33 @@ fn plot(
 
     chart.configure_mesh().draw()?;
 
-    chart
-        .draw_series(
-            data.iter()
-                .map(|(x, y)| (*x as f32, *y as f32))
-                .map(|point| Cross::new(point, 4, Into::<ShapeStyle>::into(&RED).filled())),
-        )?
-        .label(label)
-        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &RED));
+    for s in series {
+        let color = s.color;
+        chart
+            .draw_series(
+                s.data
+                    .iter()
+                    .map(|(x, y)| (*x as f32, *y as f32))
+                    .map(move |point| Cross::new(point, 4, Into::<ShapeStyle>::into(&color).filled())),
+            )?
+            .label(s.label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &color));
+
+        if let Some((intercept, slope)) = s.fit {
+            let x0 = ranged.0.start as f64;
+            let x1 = ranged.0.end as f64;
+            chart
+                .draw_series(LineSeries::new(
+                    vec![
+                        (x0 as f32, (intercept + slope * x0) as f32),
+                        (x1 as f32, (intercept + slope * x1) as f32),
+                    ],
+                    &color,
+                ))?
+                .label(format!("{} (fit)", s.label))
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], &color));
+        }
+    }
 
     chart
         .configure_series_labels()
@@ -89,6 +365,27 @@ fn plot(
     Ok(())
 }
 
+fn plot(
+    dest: &std::path::Path,
+    title: &str,
+    series: &[PlotSeries],
+    ranged: (std::ops::Range<f32>, std::ops::Range<f32>),
+    format: Format,
+    size: (u32, u32),
+) -> Result<()> {
+    let dest = dest.to_str().unwrap();
+    match format {
+        Format::Png => {
+            let root = BitMapBackend::new(dest, size).into_drawing_area();
+            render(root, title, series, ranged)
+        }
+        Format::Svg => {
+            let root = SVGBackend::new(dest, size).into_drawing_area();
+            render(root, title, series, ranged)
+        }
+    }
+}
+
 fn run() -> Result<()> {
     let args: Args = Docopt::new(USAGE)
         .and_then(|d| d.deserialize())
@@ -104,75 +401,94 @@ fn run() -> Result<()> {
         return Ok(());
     }
 
-    let label = args.flag_label;
     let title = args.flag_title;
+    let format: Format = args.flag_format.parse()?;
+    let size = parse_size(&args.flag_size)?;
+
+    let mut inputs = args
+        .flag_input
+        .iter()
+        .map(|raw| parse_input_arg(raw))
+        .collect::<Result<Vec<_>>>()?;
+
+    if let Some(file) = args.arg_file.clone() {
+        let label = file
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("input")
+            .to_owned();
+        inputs.push(InputSpec { label, path: file });
+    }
 
-    let file: std::path::PathBuf = args.arg_file.try_into()?;
-
-    let mut data = Vec::with_capacity(128);
-    let buffered = std::io::BufReader::with_capacity(4096, io::stdin());
-    let mut rdr = csv::ReaderBuilder::new()
-        .has_headers(false)
-        .delimiter(b',')
-        .flexible(true)
-        .from_reader(buffered);
+    let all_series = if inputs.is_empty() {
+        vec![parse_series("stdin", io::stdin())?]
+    } else {
+        inputs
+            .iter()
+            .map(|input| {
+                let f = std::fs::File::open(&input.path).map_err(|e| {
+                    format_err!("Failed to open input `{}` ({:?}): {}", input.label, input.path, e)
+                })?;
+                parse_series(&input.label, f)
+            })
+            .collect::<Result<Vec<Series>>>()?
+    };
 
-    let mut y_max = u32::min_value();
-    let mut x_max = u32::min_value();
-    let mut y_min = u32::max_value();
-    let mut x_min = u32::max_value();
-
-    let mut first_valid_record = false;
-    for rec in rdr.records() {
-        let rec = rec.map_err(|_e| format_err!("Failed to parse csv line"))?;
-
-        rec.deserialize::<Record>(None)
-            .map_err(|_e| format_err!("Failed to parse record"))
-            .and_then(|record: Record| {
-                first_valid_record = true;
-                if record.idx > x_max {
-                    x_max = record.idx;
-                }
-                if record.time_ms > y_max {
-                    y_max = record.time_ms;
-                }
-                if record.idx < x_min {
-                    x_min = record.idx;
+    let mut range = RangeAccumulator::default();
+    for series in &all_series {
+        range.merge(&series.range);
+    }
+    let (x_range, y_range) = range.as_ranges();
+
+    let plot_series = all_series
+        .iter()
+        .enumerate()
+        .map(|(i, series)| {
+            let color = PALETTE[i % PALETTE.len()];
+            let fit = match series.fit() {
+                Ok(coeffs) => {
+                    println!(
+                        "[{}] Fitted weight model: time = {:.4}{}",
+                        series.label,
+                        coeffs.intercept,
+                        coeffs
+                            .slopes
+                            .iter()
+                            .enumerate()
+                            .map(|(i, slope)| match slope {
+                                Some(slope) => format!(" + {:.4} * component{}", slope, i),
+                                None => format!(" + <unidentifiable> * component{}", i),
+                            })
+                            .collect::<String>()
+                    );
+                    // The chart only plots the first component on the x-axis, so for a
+                    // multi-component fit, project the plane onto that axis by holding
+                    // the remaining components at their sample mean.
+                    coeffs.slopes[0].map(|slope0| {
+                        let means = series.component_means();
+                        let intercept = coeffs.slopes[1..]
+                            .iter()
+                            .zip(means.iter().skip(1))
+                            .fold(coeffs.intercept, |acc, (slope, mean)| acc + slope.unwrap_or(0.0) * mean);
+                        (intercept, slope0)
+                    })
                 }
-                if record.time_ms < y_min {
-                    y_min = record.time_ms;
+                Err(e) => {
+                    warn!("[{}] Could not fit a regression to the benchmark data: {}", series.label, e);
+                    None
                 }
+            };
 
-                data.push(record.as_tuple());
-                Ok::<(), failure::Error>(())
-            })
-            .or_else(|e| {
-                if !first_valid_record {
-                    println!("Found header {:?}", rec);
-                    let columns = header::parse_header_columns(rec.as_slice());
-                    println!("Found header columns {:?}", columns);
-                    let info = header::parse_header_info(rec.as_slice());
-                    println!("Found header info {:?}", info);
-                    Ok::<(), failure::Error>(())
-                } else {
-                    Err(e)
-                }
-            })
-            .unwrap_or_else(|e| {
-                warn!("Failed to convert {:?}", e);
-                ()
-            });
-    }
-
-    if data.len() < 2 {
-        bail!("Only one datapoint, go home");
-    }
-    let y_max: f32 = y_max as f32;
-    let x_max: f32 = x_max as f32;
-    let y_min: f32 = y_min as f32;
-    let x_min: f32 = x_min as f32;
+            PlotSeries {
+                label: &series.label,
+                data: &series.data,
+                fit,
+                color,
+            }
+        })
+        .collect::<Vec<_>>();
 
-    plot(&file, &label, &title, &data, (x_min..x_max, y_min..y_max))?;
+    plot(&args.flag_out, &title, &plot_series, (x_range, y_range), format, size)?;
 
     Ok(())
 }
@@ -190,4 +506,20 @@ Pallet: "pallet-utility", Extrinsic: "as_sub", Steps: 30, Repeat: 11
 A,I,time
 77,0,2"#;
     }
+
+    #[test]
+    fn parse_series_detects_time_column_end_to_end() {
+        let csv = "Pallet: \"pallet-utility\", Extrinsic: \"as_sub\", Steps: 30, Repeat: 11\nA,I,time\n77,0,2\n80,1,3\n90,2,4\n";
+        let series = parse_series("test", io::Cursor::new(csv)).unwrap();
+
+        assert_eq!(series.component_count, 2);
+        assert_eq!(series.data, vec![(77.0, 2.0), (80.0, 3.0), (90.0, 4.0)]);
+        assert_eq!(series.times, vec![2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn parse_series_rejects_header_without_time_column() {
+        let csv = "A,I,B\n77,0,2\n80,1,3\n";
+        assert!(parse_series("test", io::Cursor::new(csv)).is_err());
+    }
 }
@@ -0,0 +1,267 @@
+//! Least-squares weight extraction, modelled after how `frame-benchmarking`
+//! derives linear weight formulas (`time = \beta0 + \Sigma \beta_i \cdot component_i`)
+//! from raw step measurements.
+
+use std::collections::HashSet;
+
+use common_failures::prelude::*;
+use failure::bail;
+
+/// Coefficients of a fitted linear weight model.
+///
+/// `slopes[i]` is `None` when the matching component could not be identified,
+/// e.g. because it never varied across the sampled rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coefficients {
+    pub intercept: f64,
+    pub slopes: Vec<Option<f64>>,
+}
+
+/// Fit `time = intercept + slope * x` to a single `(x, y)` series.
+pub fn fit_simple(data: &[(f64, f64)]) -> Result<Coefficients> {
+    if data.len() < 2 {
+        bail!("Need at least two data points to fit a regression");
+    }
+
+    let n = data.len() as f64;
+    let x_mean = data.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let y_mean = data.iter().map(|(_, y)| y).sum::<f64>() / n;
+
+    let mut num = 0f64;
+    let mut den = 0f64;
+    for (x, y) in data {
+        num += (x - x_mean) * (y - y_mean);
+        den += (x - x_mean).powi(2);
+    }
+
+    if den == 0.0 {
+        bail!("x does not vary across samples, slope is unidentifiable");
+    }
+
+    let slope = num / den;
+    let intercept = y_mean - slope * x_mean;
+
+    Ok(Coefficients {
+        intercept,
+        slopes: vec![Some(slope)],
+    })
+}
+
+/// Fit `time = beta0 + Sigma beta_i * component_i` to a multi-component
+/// benchmark, solving the normal equations `(X^T X) beta = X^T y` by
+/// Gaussian elimination with partial pivoting.
+///
+/// `rows[r][c]` is the value of component `c` for sample `r`; `y[r]` is the
+/// matching `time` value. A component whose column yields an unsolvable
+/// pivot (e.g. because it never varies) is reported as `None` rather than
+/// causing a division by zero.
+pub fn fit_multi(rows: &[Vec<f64>], y: &[f64]) -> Result<Coefficients> {
+    if rows.len() != y.len() {
+        bail!("Component rows and y-values must have the same length");
+    }
+    if rows.is_empty() {
+        bail!("No samples to fit a regression");
+    }
+
+    let components = rows[0].len();
+    if rows.iter().any(|row| row.len() != components) {
+        bail!("All component rows must have the same number of columns");
+    }
+
+    let required = components + 1;
+    if distinct_row_count(rows) < required {
+        bail!(
+            "Need at least {} distinct rows to fit {} component(s), found fewer",
+            required,
+            components
+        );
+    }
+
+    // Design matrix X: a leading column of 1s followed by one column per component.
+    let width = components + 1;
+    let design: Vec<Vec<f64>> = rows
+        .iter()
+        .map(|row| {
+            let mut r = Vec::with_capacity(width);
+            r.push(1.0);
+            r.extend_from_slice(row);
+            r
+        })
+        .collect();
+
+    // Normal equations (X^T X) beta = X^T y.
+    let mut xtx = vec![vec![0f64; width]; width];
+    let mut xty = vec![0f64; width];
+    for (row, &yi) in design.iter().zip(y.iter()) {
+        for i in 0..width {
+            xty[i] += row[i] * yi;
+            for j in 0..width {
+                xtx[i][j] += row[i] * row[j];
+            }
+        }
+    }
+
+    let beta = solve_gaussian_partial_pivot(xtx, xty);
+
+    Ok(Coefficients {
+        intercept: beta[0].unwrap_or(0.0),
+        slopes: beta[1..].to_vec(),
+    })
+}
+
+fn distinct_row_count(rows: &[Vec<f64>]) -> usize {
+    let mut count = 0;
+    for (i, row) in rows.iter().enumerate() {
+        if rows[..i].iter().all(|seen| seen != row) {
+            count += 1;
+        }
+    }
+    count
+}
+
+const PIVOT_EPSILON: f64 = 1e-9;
+
+/// Solve `a * x = b` for the coefficients that can be identified. A column
+/// whose pivot collapses to (near) zero (e.g. because the matching component
+/// never varies, or duplicates another column) is dropped from the system
+/// entirely and reported as `None`, and the remaining coefficients are
+/// re-solved over the reduced, full-rank system rather than left polluted by
+/// the dropped column's lingering entries.
+fn solve_gaussian_partial_pivot(a: Vec<Vec<f64>>, b: Vec<f64>) -> Vec<Option<f64>> {
+    let n = b.len();
+    let unidentifiable = find_unidentifiable_columns(a.clone(), b.clone());
+
+    let keep: Vec<usize> = (0..n).filter(|i| !unidentifiable.contains(i)).collect();
+    let reduced_a: Vec<Vec<f64>> = keep
+        .iter()
+        .map(|&i| keep.iter().map(|&j| a[i][j]).collect())
+        .collect();
+    let reduced_b: Vec<f64> = keep.iter().map(|&i| b[i]).collect();
+    let reduced_beta = solve_gaussian(reduced_a, reduced_b);
+
+    let mut beta = vec![None; n];
+    for (i, v) in keep.into_iter().zip(reduced_beta) {
+        beta[i] = Some(v);
+    }
+    beta
+}
+
+/// Run forward elimination solely to discover which columns collapse to a
+/// (near) zero pivot; any values produced along the way are discarded, only
+/// the set of unidentifiable column indices is kept. A column marked
+/// unidentifiable is zeroed out across every row so it can't skew the pivot
+/// chosen for later columns.
+fn find_unidentifiable_columns(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> HashSet<usize> {
+    let n = b.len();
+    let mut unidentifiable = HashSet::new();
+
+    for col in 0..n {
+        let pivot_row = (col..n)
+            .max_by(|&r1, &r2| a[r1][col].abs().partial_cmp(&a[r2][col].abs()).unwrap())
+            .unwrap();
+
+        if a[pivot_row][col].abs() < PIVOT_EPSILON {
+            unidentifiable.insert(col);
+            for row in a.iter_mut() {
+                row[col] = 0.0;
+            }
+            continue;
+        }
+
+        a.swap(col, pivot_row);
+        b.swap(col, pivot_row);
+
+        for row in (col + 1)..n {
+            let factor = a[row][col] / a[col][col];
+            for k in col..n {
+                a[row][k] -= factor * a[col][k];
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    unidentifiable
+}
+
+/// Gaussian elimination with partial pivoting for a system assumed to be
+/// full rank.
+fn solve_gaussian(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Vec<f64> {
+    let n = b.len();
+
+    for col in 0..n {
+        let pivot_row = (col..n)
+            .max_by(|&r1, &r2| a[r1][col].abs().partial_cmp(&a[r2][col].abs()).unwrap())
+            .unwrap();
+
+        a.swap(col, pivot_row);
+        b.swap(col, pivot_row);
+
+        for row in (col + 1)..n {
+            let factor = a[row][col] / a[col][col];
+            for k in col..n {
+                a[row][k] -= factor * a[col][k];
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+
+    let mut x = vec![0f64; n];
+    for row in (0..n).rev() {
+        let sum: f64 = b[row] - ((row + 1)..n).map(|k| a[row][k] * x[k]).sum::<f64>();
+        x[row] = sum / a[row][row];
+    }
+    x
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn simple_fit_recovers_known_line() {
+        let data = vec![(0.0, 1.0), (1.0, 3.0), (2.0, 5.0), (3.0, 7.0)];
+        let fit = fit_simple(&data).unwrap();
+        assert!((fit.intercept - 1.0).abs() < 1e-9);
+        assert!((fit.slopes[0].unwrap() - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn simple_fit_rejects_constant_x() {
+        let data = vec![(1.0, 1.0), (1.0, 2.0), (1.0, 3.0)];
+        assert!(fit_simple(&data).is_err());
+    }
+
+    #[test]
+    fn multi_fit_recovers_known_plane() {
+        // time = 1 + 2*a + 3*i
+        let rows = vec![
+            vec![0.0, 0.0],
+            vec![1.0, 0.0],
+            vec![0.0, 1.0],
+            vec![1.0, 1.0],
+        ];
+        let y: Vec<f64> = rows.iter().map(|r| 1.0 + 2.0 * r[0] + 3.0 * r[1]).collect();
+        let fit = fit_multi(&rows, &y).unwrap();
+        assert!((fit.intercept - 1.0).abs() < 1e-6);
+        assert!((fit.slopes[0].unwrap() - 2.0).abs() < 1e-6);
+        assert!((fit.slopes[1].unwrap() - 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn multi_fit_marks_constant_component_unidentifiable() {
+        // time = 5 + 1*comp1; comp0 is constant and can't be identified.
+        let rows = vec![vec![1.0, 0.0], vec![1.0, 1.0], vec![1.0, 2.0]];
+        let y = vec![5.0, 6.0, 7.0];
+        let fit = fit_multi(&rows, &y).unwrap();
+        assert!(fit.slopes[0].is_none());
+        assert!((fit.intercept - 5.0).abs() < 1e-6);
+        assert!((fit.slopes[1].unwrap() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn multi_fit_requires_enough_distinct_rows() {
+        let rows = vec![vec![1.0, 2.0], vec![1.0, 2.0], vec![1.0, 2.0]];
+        let y = vec![1.0, 1.0, 1.0];
+        assert!(fit_multi(&rows, &y).is_err());
+    }
+}